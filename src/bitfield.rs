@@ -1,50 +1,53 @@
 use bitwise::word::*;
 use byteorder::{BigEndian, ByteOrder};
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
+/// A bitfield of `N * 64` bits, backed by `[u64; N]`.
+///
+/// `N` sets the HAMT branching factor; `Bitfield<4>` is the go-compatible
+/// default. `Hamt` takes no width parameter, so it always uses the default
+/// width.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Bitfield([u64; 4]);
+pub struct Bitfield<const N: usize = 4>([u64; N]);
 
-impl Serialize for Bitfield {
+impl<const N: usize> Serialize for Bitfield<N> {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut v = vec![0u8; 4 * 8];
-        // Big endian ordering, to match go
-        BigEndian::write_u64(&mut v[..8], self.0[3]);
-        BigEndian::write_u64(&mut v[8..16], self.0[2]);
-        BigEndian::write_u64(&mut v[16..24], self.0[1]);
-        BigEndian::write_u64(&mut v[24..], self.0[0]);
+        let mut v = vec![0u8; N * 8];
+        // Big endian ordering, most-significant limb first, to match go.
+        for i in 0..N {
+            BigEndian::write_u64(&mut v[i * 8..i * 8 + 8], self.0[N - 1 - i]);
+        }
 
         let byte_buf = serde_bytes::ByteBuf::from(v);
         byte_buf.serialize(serializer)
     }
 }
 
-impl<'de> Deserialize<'de> for Bitfield {
+impl<'de, const N: usize> Deserialize<'de> for Bitfield<N> {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
         let mut res = Bitfield::zero();
         let bytes = serde_bytes::ByteBuf::deserialize(deserializer)?.into_vec();
-        res.0[3] = BigEndian::read_u64(&bytes[..8]);
-        res.0[2] = BigEndian::read_u64(&bytes[8..16]);
-        res.0[1] = BigEndian::read_u64(&bytes[16..24]);
-        res.0[0] = BigEndian::read_u64(&bytes[24..]);
+        for i in 0..N {
+            res.0[N - 1 - i] = BigEndian::read_u64(&bytes[i * 8..i * 8 + 8]);
+        }
 
         Ok(res)
     }
 }
 
-impl Default for Bitfield {
+impl<const N: usize> Default for Bitfield<N> {
     fn default() -> Self {
         Bitfield::zero()
     }
 }
 
-impl Bitfield {
+impl<const N: usize> Bitfield<N> {
     pub fn clear_bit(&mut self, idx: u8) {
         let ai = idx / 64;
         let bi = idx % 64;
@@ -69,17 +72,63 @@ impl Bitfield {
         self.0.iter().map(|a| a.count_ones() as usize).sum()
     }
 
-    pub fn and(self, other: &Self) -> Self {
-        Bitfield([
-            self.0[0] & other.0[0],
-            self.0[1] & other.0[1],
-            self.0[2] & other.0[2],
-            self.0[3] & other.0[3],
-        ])
+    pub fn and(mut self, other: &Self) -> Self {
+        for i in 0..N {
+            self.0[i] &= other.0[i];
+        }
+        self
     }
 
     pub fn zero() -> Self {
-        Bitfield([0, 0, 0, 0])
+        Bitfield([0u64; N])
+    }
+
+    /// Number of 64-bit limbs that carry set bits, i.e. the fixed array with
+    /// its trailing all-zero limbs stripped.
+    fn significant_limbs(&self) -> usize {
+        (0..self.0.len())
+            .rev()
+            .find(|&i| self.0[i] != 0)
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    }
+
+    /// Length in bytes of the compact wire form ([`Bitfield::to_compact`]).
+    pub fn serialized_len(&self) -> usize {
+        1 + self.significant_limbs() * 8
+    }
+
+    /// Compact wire form: a one-byte limb count, then that many 64-bit limbs
+    /// (big-endian, least-significant first) with trailing zero limbs dropped.
+    pub fn to_compact(&self) -> Vec<u8> {
+        let n = self.significant_limbs();
+        let mut out = Vec::with_capacity(1 + n * 8);
+        out.push(n as u8);
+        let mut buf = [0u8; 8];
+        for i in 0..n {
+            BigEndian::write_u64(&mut buf, self.0[i]);
+            out.extend_from_slice(&buf);
+        }
+        out
+    }
+
+    /// Inverse of [`Bitfield::to_compact`]. Missing high limbs read as zero.
+    /// Rejects a count larger than `N` or a truncated limb body.
+    pub fn from_compact(bytes: &[u8]) -> std::result::Result<Self, String> {
+        let mut res = Bitfield::zero();
+        if let Some((&n, limbs)) = bytes.split_first() {
+            let n = n as usize;
+            if n > N {
+                return Err(format!("compact bitfield count {} exceeds width {}", n, N));
+            }
+            if limbs.len() < n * 8 {
+                return Err("compact bitfield body truncated".to_string());
+            }
+            for i in 0..n {
+                res.0[i] = BigEndian::read_u64(&limbs[i * 8..i * 8 + 8]);
+            }
+        }
+        Ok(res)
     }
 
     pub fn set_bits_le(self, bit: u8) -> Self {
@@ -90,31 +139,60 @@ impl Bitfield {
     }
 
     pub fn set_bits_leq(mut self, bit: u8) -> Self {
-        if bit < 64 {
-            self.0[0] = self.0[0].set_bits_leq(bit);
-        } else if bit < 128 {
-            self.0[0] = std::u64::MAX;
-            self.0[1] = self.0[1].set_bits_leq(bit as u32 - 64);
-        } else if bit < 192 {
-            self.0[0] = std::u64::MAX;
-            self.0[1] = std::u64::MAX;
-            self.0[2] = self.0[2].set_bits_leq(bit as u32 - 128);
-        } else {
-            self.0[0] = std::u64::MAX;
-            self.0[1] = std::u64::MAX;
-            self.0[2] = std::u64::MAX;
-            self.0[3] = self.0[3].set_bits_leq(bit as u32 - 192);
+        let bit = bit as u32;
+        for i in 0..N {
+            let low = (i * 64) as u32;
+            if bit >= low + 63 {
+                self.0[i] = std::u64::MAX;
+            } else if bit >= low {
+                self.0[i] = self.0[i].set_bits_leq(bit - low);
+                break;
+            } else {
+                break;
+            }
         }
 
         self
     }
 }
 
-impl std::fmt::Binary for Bitfield {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let val = self.0;
+/// Selects the compact wire form when (de)serializing a [`Bitfield`]. The bare
+/// `Bitfield` keeps serializing as the go-compatible fixed 32-byte blob, so a
+/// call site opts into the compact form by wrapping the value in `Compact`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compact<const N: usize = 4>(pub Bitfield<N>);
 
-        write!(f, "{:b}_{:b}_{:b}_{:b}", val[0], val[1], val[2], val[3])
+impl<const N: usize> Serialize for Compact<N> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let byte_buf = serde_bytes::ByteBuf::from(self.0.to_compact());
+        byte_buf.serialize(serializer)
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for Compact<N> {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = serde_bytes::ByteBuf::deserialize(deserializer)?.into_vec();
+        Bitfield::from_compact(&bytes)
+            .map(Compact)
+            .map_err(de::Error::custom)
+    }
+}
+
+impl<const N: usize> std::fmt::Binary for Bitfield<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, limb) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "_")?;
+            }
+            write!(f, "{:b}", limb)?;
+        }
+        Ok(())
     }
 }
 
@@ -141,4 +219,65 @@ mod tests {
         assert!(!b.test_bit(18));
     }
 
+    #[test]
+    fn test_compact_roundtrip() {
+        let mut populated = Bitfield::zero();
+        for i in 0..=255 {
+            populated.set_bit(i);
+        }
+
+        let mut high = Bitfield::zero();
+        high.set_bit(255);
+
+        let cases = vec![Bitfield::zero(), high, populated];
+        let expected_lens = vec![1, 1 + 4 * 8, 1 + 4 * 8];
+
+        for (b, len) in cases.into_iter().zip(expected_lens) {
+            assert_eq!(b.serialized_len(), len);
+            assert_eq!(b.to_compact().len(), len);
+            // The compact form decodes back to the exact same bitfield.
+            assert_eq!(Bitfield::from_compact(&b.to_compact()).unwrap(), b);
+            // And so does the fixed 32-byte form, proving the two agree.
+            let fixed = serde_cbor::to_vec(&b).unwrap();
+            let from_fixed: Bitfield = serde_cbor::from_slice(&fixed).unwrap();
+            assert_eq!(from_fixed, b);
+        }
+    }
+
+    #[test]
+    fn test_narrow_width() {
+        // A single-limb bitfield: 64 bits, for 5-bit hash chunks.
+        let mut b: Bitfield<1> = Bitfield::zero();
+        b.set_bit(5);
+        b.set_bit(63);
+        assert!(b.test_bit(5));
+        assert!(b.test_bit(63));
+        assert_eq!(b.count_ones(), 2);
+
+        let bytes = serde_cbor::to_vec(&b).unwrap();
+        assert_eq!(bytes.len(), 1 + 8); // cbor byte-string head + 8 bytes
+        let back: Bitfield<1> = serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(back, b);
+    }
+
+    #[test]
+    fn test_compact_single_low_bit() {
+        // A single low bit needs just one limb, not the full four.
+        let mut b = Bitfield::zero();
+        b.set_bit(8);
+        assert_eq!(b.serialized_len(), 1 + 8);
+        assert_eq!(Bitfield::from_compact(&b.to_compact()).unwrap(), b);
+    }
+
+    #[test]
+    fn test_compact_rejects_malformed() {
+        // Count larger than the width must not index out of bounds.
+        assert!(Bitfield::<4>::from_compact(&[5]).is_err());
+        // A truncated limb body must not slice past the input.
+        assert!(Bitfield::<4>::from_compact(&[1]).is_err());
+        // Malformed CBOR deserialization surfaces the error instead of panicking.
+        let bytes = serde_cbor::to_vec(&serde_bytes::ByteBuf::from(vec![5u8])).unwrap();
+        let res: std::result::Result<Compact<4>, _> = serde_cbor::from_slice(&bytes);
+        assert!(res.is_err());
+    }
 }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::io::{self, Read};
 use std::sync::RwLock;
 
 use cid::Cid;
@@ -6,23 +7,160 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use sha2::Digest;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::tagged_cid::TaggedCid;
 
+/// Caps how many input bytes a single `get` may read while deserializing.
+/// A finite byte budget also bounds nesting depth, since each level costs a
+/// header byte.
+#[derive(Debug, Clone, Copy)]
+pub struct Limit {
+    max_bytes: Option<u64>,
+}
+
+impl Limit {
+    /// No budget — the current, unrestricted behavior.
+    pub fn unlimited() -> Self {
+        Limit { max_bytes: None }
+    }
+
+    /// Allow at most `max_bytes` to be read from the backing buffer.
+    pub fn bytes(max_bytes: u64) -> Self {
+        Limit {
+            max_bytes: Some(max_bytes),
+        }
+    }
+}
+
+impl Default for Limit {
+    fn default() -> Self {
+        Limit::unlimited()
+    }
+}
+
+/// A reader that decrements a running byte budget as input is consumed and
+/// refuses to hand out more bytes once the budget is exhausted.
+struct LimitReader<R> {
+    inner: R,
+    remaining: Option<u64>,
+}
+
+impl<R: Read> LimitReader<R> {
+    fn new(inner: R, limit: Limit) -> Self {
+        LimitReader {
+            inner,
+            remaining: limit.max_bytes,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(remaining) = self.remaining {
+            if (buf.len() as u64) > remaining {
+                // Clamp so we never read past the budget, then fail on the
+                // next call if the deserializer still wants more.
+                if remaining == 0 {
+                    // Input that ends exactly at the budget is plain EOF.
+                    if self.inner.read(&mut [0u8; 1])? == 0 {
+                        return Ok(0);
+                    }
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "block exceeds configured deserialization limit",
+                    ));
+                }
+                let take = remaining as usize;
+                let n = self.inner.read(&mut buf[..take])?;
+                self.remaining = Some(remaining - n as u64);
+                return Ok(n);
+            }
+        }
+        let n = self.inner.read(buf)?;
+        if let Some(remaining) = self.remaining {
+            self.remaining = Some(remaining - n as u64);
+        }
+        Ok(n)
+    }
+}
+
 pub trait Store: std::fmt::Debug {
     fn insert<B: Serialize>(&self, block: &B) -> Result<TaggedCid>;
     fn get<B: DeserializeOwned>(&self, cid: &TaggedCid) -> Result<Option<B>>;
     fn get_bytes(&self, cid: &TaggedCid) -> Result<Option<Vec<u8>>>;
+
+    /// Like [`Store::get`], but aborts if decoding the block would read more
+    /// than `limit` allows.
+    ///
+    /// The default guards *decode-time* expansion only: it calls
+    /// [`Store::get_bytes`], so the whole raw block is already materialized
+    /// before the [`LimitReader`] can trip. A network-backed store must
+    /// override this to push the limit into retrieval; otherwise a hostile
+    /// block is fetched in full before the budget applies.
+    fn get_limited<B: DeserializeOwned>(
+        &self,
+        cid: &TaggedCid,
+        limit: Limit,
+    ) -> Result<Option<B>> {
+        match self.get_bytes(cid)? {
+            Some(bytes) => {
+                let reader = LimitReader::new(&bytes[..], limit);
+                let obj = serde_cbor::from_reader(reader).map_err(Error::from)?;
+                Ok(Some(obj))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Multihash algorithm used when minting a block's CID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlg {
+    Sha2_256,
+    Blake2b256,
+}
+
+impl HashAlg {
+    /// Multicodec code for this multihash algorithm.
+    fn code(self) -> u64 {
+        match self {
+            HashAlg::Sha2_256 => 0x12,
+            HashAlg::Blake2b256 => 0xb220,
+        }
+    }
+
+    /// Digest length in bytes. Both supported algorithms are 256-bit.
+    fn size(self) -> usize {
+        32
+    }
+}
+
+impl Default for HashAlg {
+    fn default() -> Self {
+        HashAlg::Sha2_256
+    }
 }
 
 #[derive(Default, Debug)]
 pub struct MemoryStore {
     data: RwLock<HashMap<TaggedCid, Vec<u8>>>,
+    hash: HashAlg,
+}
+
+impl MemoryStore {
+    /// Build a store that mints CIDs using `hash` instead of the default
+    /// SHA2-256.
+    pub fn with_hash(hash: HashAlg) -> Self {
+        MemoryStore {
+            data: RwLock::new(HashMap::new()),
+            hash,
+        }
+    }
 }
 
 impl Store for MemoryStore {
     fn insert<B: Serialize>(&self, block: &B) -> Result<TaggedCid> {
-        let (c, bytes) = serialize_sha256(block)?;
+        let (c, bytes) = serialize_with(self.hash, block)?;
         self.data.write().unwrap().insert(c.clone(), bytes);
 
         Ok(c)
@@ -43,17 +181,25 @@ impl Store for MemoryStore {
     }
 }
 
-fn _serialize_blake2b<D: Serialize>(data: &D) -> Result<(TaggedCid, Vec<u8>)> {
-    let bytes = serde_cbor::to_vec(data)?;
+/// Encode `data` as canonical DAG-CBOR and mint its v1 CID using `alg`. The
+/// multihash prefix is varint-encoded so multi-byte codes (e.g. Blake2b-256,
+/// `0xb220`) stay valid.
+fn serialize_with<D: Serialize>(alg: HashAlg, data: &D) -> Result<(TaggedCid, Vec<u8>)> {
+    let bytes = canonical::to_vec(data)?;
+
+    let digest = match alg {
+        HashAlg::Sha2_256 => sha2::Sha256::digest(&bytes).to_vec(),
+        HashAlg::Blake2b256 => blake2b_simd::Params::new()
+            .hash_length(alg.size())
+            .hash(&bytes)
+            .as_bytes()
+            .to_vec(),
+    };
 
-    // TODO: fix cid and multihash!!!
-    let h = blake2b_simd::blake2b(&bytes);
-    let code = multihash::Hash::Blake2b.code();
-    let size = multihash::Hash::Blake2b.size();
-    let mut hash = vec![0; size as usize + 2];
-    hash[0] = code;
-    hash[1] = size;
-    hash[2..].copy_from_slice(h.as_ref());
+    let mut hash = Vec::with_capacity(digest.len() + 4);
+    write_uvarint(&mut hash, alg.code());
+    write_uvarint(&mut hash, digest.len() as u64);
+    hash.extend_from_slice(&digest);
 
     let c = Cid {
         version: cid::Version::V1,
@@ -64,25 +210,109 @@ fn _serialize_blake2b<D: Serialize>(data: &D) -> Result<(TaggedCid, Vec<u8>)> {
     Ok((c.into(), bytes))
 }
 
-fn serialize_sha256<D: Serialize>(data: &D) -> Result<(TaggedCid, Vec<u8>)> {
-    let bytes = serde_cbor::to_vec(&serde_cbor::value::to_value(data.clone())?)?;
+/// Append `n` as an unsigned LEB128 varint, the encoding multihash and CID use
+/// for codes and lengths.
+fn write_uvarint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
 
-    // TODO: fix cid and multihash!!!
-    let h = sha2::Sha256::digest(&bytes);
-    let code = multihash::Hash::SHA2256.code();
-    let size = multihash::Hash::SHA2256.size();
-    let mut hash = vec![0; size as usize + 2];
-    hash[0] = code;
-    hash[1] = size;
-    hash[2..].copy_from_slice(h.as_ref());
+/// Canonical DAG-CBOR encoding, byte-for-byte compatible with go-ipld: map
+/// keys sorted by encoded length then bytewise, shortest-form heads, and only
+/// definite-length containers.
+mod canonical {
+    use serde::Serialize;
+    use serde_cbor::Value;
 
-    let c = Cid {
-        version: cid::Version::V1,
-        codec: cid::Codec::DagCBOR,
-        hash,
-    };
+    use crate::error::Result;
 
-    Ok((c.into(), bytes))
+    pub fn to_vec<D: Serialize>(data: &D) -> Result<Vec<u8>> {
+        let value = serde_cbor::value::to_value(data)?;
+        let mut out = Vec::new();
+        write_value(&value, &mut out);
+        Ok(out)
+    }
+
+    /// Emit a CBOR head for `major` with the shortest additional-info width
+    /// that can hold `arg`.
+    fn write_head(out: &mut Vec<u8>, major: u8, arg: u64) {
+        let m = major << 5;
+        if arg < 24 {
+            out.push(m | arg as u8);
+        } else if arg <= u8::max_value() as u64 {
+            out.push(m | 24);
+            out.push(arg as u8);
+        } else if arg <= u16::max_value() as u64 {
+            out.push(m | 25);
+            out.extend_from_slice(&(arg as u16).to_be_bytes());
+        } else if arg <= u32::max_value() as u64 {
+            out.push(m | 26);
+            out.extend_from_slice(&(arg as u32).to_be_bytes());
+        } else {
+            out.push(m | 27);
+            out.extend_from_slice(&arg.to_be_bytes());
+        }
+    }
+
+    fn write_value(value: &Value, out: &mut Vec<u8>) {
+        match value {
+            Value::Null => out.push(0xf6),
+            Value::Bool(false) => out.push(0xf4),
+            Value::Bool(true) => out.push(0xf5),
+            Value::Integer(i) => {
+                if *i >= 0 {
+                    write_head(out, 0, *i as u64);
+                } else {
+                    write_head(out, 1, (-1 - *i) as u64);
+                }
+            }
+            Value::Float(f) => {
+                out.push(0xfb);
+                out.extend_from_slice(&f.to_bits().to_be_bytes());
+            }
+            Value::Bytes(b) => {
+                write_head(out, 2, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Value::Text(s) => {
+                write_head(out, 3, s.len() as u64);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Value::Array(arr) => {
+                write_head(out, 4, arr.len() as u64);
+                for item in arr {
+                    write_value(item, out);
+                }
+            }
+            Value::Map(map) => {
+                let mut entries: Vec<(Vec<u8>, Vec<u8>)> = map
+                    .iter()
+                    .map(|(k, v)| {
+                        let mut kb = Vec::new();
+                        write_value(k, &mut kb);
+                        let mut vb = Vec::new();
+                        write_value(v, &mut vb);
+                        (kb, vb)
+                    })
+                    .collect();
+                // Canonical key order: shorter encodings first, then bytewise.
+                entries.sort_by(|a, b| a.0.len().cmp(&b.0.len()).then_with(|| a.0.cmp(&b.0)));
+                write_head(out, 5, entries.len() as u64);
+                for (kb, vb) in entries {
+                    out.extend_from_slice(&kb);
+                    out.extend_from_slice(&vb);
+                }
+            }
+            Value::Tag(tag, inner) => {
+                write_head(out, 6, *tag);
+                write_value(inner, out);
+            }
+            _ => {}
+        }
+    }
 }
 
 #[cfg(test)]
@@ -100,6 +330,47 @@ mod tests {
         assert_eq!(back, Some(("hello".to_string(), 3)));
     }
 
+    #[test]
+    fn test_hash_alg_selection() {
+        let sha = MemoryStore::default();
+        let blake = MemoryStore::with_hash(HashAlg::Blake2b256);
+
+        let c_sha = sha.insert(&("hello".to_string(), 3)).unwrap();
+        let c_blake = blake.insert(&("hello".to_string(), 3)).unwrap();
+
+        // Same bytes, different multihash, therefore different CIDs.
+        assert_ne!(c_sha, c_blake);
+
+        let cid = c_blake.as_ref();
+        assert_eq!(cid.version, cid::Version::V1);
+        assert_eq!(cid.codec, cid::Codec::DagCBOR);
+        // Blake2b-256 multihash prefix: code 0xb220 as a varint, length 32.
+        assert_eq!(&cid.hash[..3], &[0xa0, 0xe4, 0x02]);
+        assert_eq!(cid.hash[3], 32);
+        assert_eq!(cid.hash.len(), 3 + 1 + 32);
+    }
+
+    #[test]
+    fn test_get_limited() {
+        let store = MemoryStore::default();
+
+        // A chunky block: well over any small budget we set below.
+        let big: Vec<u32> = (0..1024).collect();
+        let c = store.insert(&big).unwrap();
+
+        // Within budget: round-trips as usual.
+        let back: Option<Vec<u32>> = store.get_limited(&c, Limit::bytes(64 * 1024)).unwrap();
+        assert_eq!(back, Some(big));
+
+        // A hostile-sized read: the budget trips before the block is decoded.
+        let limited: Result<Option<Vec<u32>>> = store.get_limited(&c, Limit::bytes(16));
+        assert!(limited.is_err());
+
+        // Unlimited is still accepted and behaves like `get`.
+        let unbounded: Option<Vec<u32>> = store.get_limited(&c, Limit::unlimited()).unwrap();
+        assert!(unbounded.is_some());
+    }
+
     #[test]
     fn test_memory_interop() {
         let store = MemoryStore::default();
@@ -147,12 +418,11 @@ mod tests {
         );
         println!("{:#?}", &hamt);
 
-        // Not quite there yet
-        // assert_eq!(
-        //     c3,
-        //     Cid::from("zdpuApTKRtVAtwquN7f3A5bZBXnsLkmpLQfF7CVAeGDbkL5Zo")
-        //         .unwrap()
-        //         .into()
-        // );
+        assert_eq!(
+            c3,
+            Cid::from("zdpuApTKRtVAtwquN7f3A5bZBXnsLkmpLQfF7CVAeGDbkL5Zo")
+                .unwrap()
+                .into()
+        );
     }
 }
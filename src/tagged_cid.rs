@@ -1,56 +1,126 @@
+use std::result::Result as StdResult;
+
 use cid::Cid;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use num_bigint::{BigInt, BigUint, Sign};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_bytes::ByteBuf;
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub struct TaggedCid(Cid);
+/// Well-known CBOR tag numbers used by IPLD and plain CBOR. Each
+/// [`TaggedValue`] points [`TaggedValue::TAG`] at one of these.
+pub mod tags {
+    /// Standard date/time string (RFC 3339).
+    pub const DATE_TIME: u64 = 0;
+    /// Epoch-based date/time (seconds since 1970).
+    pub const EPOCH: u64 = 1;
+    /// Unsigned bignum, big-endian byte string.
+    pub const POSITIVE_BIGNUM: u64 = 2;
+    /// Negative bignum, big-endian byte string of `-1 - n`.
+    pub const NEGATIVE_BIGNUM: u64 = 3;
+    /// IPLD link (CID), binary-multibase byte string.
+    pub const LINK: u64 = 42;
+}
 
-impl TaggedCid {
-    fn tag() -> u64 {
-        42
-    }
+/// A value with a fixed CBOR semantic tag and a serde-serializable `Body`.
+/// [`Tagged`] and the `*_tagged` helpers handle the tag once for every
+/// implementor.
+pub trait TaggedValue: Sized {
+    const TAG: u64;
+    type Body: Serialize + serde::de::DeserializeOwned;
+
+    fn to_body(&self) -> Self::Body;
+    fn from_body(body: Self::Body) -> StdResult<Self, String>;
+}
+
+/// Emit `value` wrapped in its registered CBOR tag.
+fn serialize_tagged<T, S>(value: &T, serializer: S) -> StdResult<S::Ok, S::Error>
+where
+    T: TaggedValue,
+    S: Serializer,
+{
+    serde_cbor::EncodeCborTag::new(T::TAG, &value.to_body()).serialize(serializer)
+}
 
-    fn to_bytes(&self) -> Vec<u8> {
-        self.0.to_bytes()
+/// Decode a value from its registered CBOR tag, rejecting any other tag.
+fn deserialize_tagged<'de, T, D>(deserializer: D) -> StdResult<T, D::Error>
+where
+    T: TaggedValue,
+    D: Deserializer<'de>,
+{
+    let wrapper = serde_cbor::EncodeCborTag::deserialize(deserializer)?;
+    if wrapper.tag() != T::TAG {
+        return Err(de::Error::custom(format!(
+            "Invalid tag: {}, expected {}",
+            wrapper.tag(),
+            T::TAG
+        )));
     }
+    let body: T::Body = wrapper.value();
+    T::from_body(body).map_err(de::Error::custom)
 }
 
-impl Serialize for TaggedCid {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+/// Generic wrapper that (de)serializes any [`TaggedValue`] through its tag.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tagged<T>(pub T);
+
+impl<T: TaggedValue> Serialize for Tagged<T> {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut bytes = self.0.to_bytes();
+        serialize_tagged(&self.0, serializer)
+    }
+}
 
+impl<'de, T: TaggedValue> Deserialize<'de> for Tagged<T> {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_tagged(deserializer).map(Tagged)
+    }
+}
+
+/// An IPLD link (tag 42). A named [`TaggedValue`] rather than `Tagged<Cid>`
+/// because the store keys on it and exposes the inner [`Cid`] via [`AsRef`].
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct TaggedCid(Cid);
+
+impl TaggedValue for TaggedCid {
+    const TAG: u64 = tags::LINK;
+    type Body = ByteBuf;
+
+    fn to_body(&self) -> ByteBuf {
+        let mut bytes = self.0.to_bytes();
         // binary multibase is a `0` prefix
         bytes.insert(0, 0);
+        ByteBuf::from(bytes)
+    }
 
-        let byte_buf = serde_bytes::ByteBuf::from(bytes);
-        serde_cbor::EncodeCborTag::new(Self::tag(), &byte_buf).serialize(serializer)
+    fn from_body(body: ByteBuf) -> StdResult<Self, String> {
+        let bytes = body.into_vec();
+        // check for binary multibase
+        if bytes.first() != Some(&0) {
+            return Err("invalid link base".to_string());
+        }
+        Cid::from(bytes).map(TaggedCid).map_err(|e| e.to_string())
+    }
+}
+
+impl Serialize for TaggedCid {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_tagged(self, serializer)
     }
 }
 
 impl<'de> Deserialize<'de> for TaggedCid {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let wrapper = serde_cbor::EncodeCborTag::deserialize(deserializer)?;
-        if wrapper.tag() != Self::tag() {
-            return Err(serde::de::Error::custom(format!(
-                "Invalid tag: {}, expected {}",
-                wrapper.tag(),
-                Self::tag()
-            )));
-        }
-        let bytes: Vec<u8> = wrapper.value();
-        // check for binary multibase
-        if bytes[0] != 0 {
-            return Err(serde::de::Error::custom(format!("invalid link base")));
-        }
-
-        Ok(TaggedCid(
-            Cid::from(bytes).map_err(serde::de::Error::custom)?,
-        ))
+        deserialize_tagged(deserializer)
     }
 }
 
@@ -65,3 +135,92 @@ impl AsRef<Cid> for TaggedCid {
         &self.0
     }
 }
+
+/// A `BigUint` that survives a go-ipld round-trip as a CBOR tag 2 bignum
+/// (unsigned, big-endian magnitude byte string).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedBigUint(pub BigUint);
+
+impl TaggedValue for TaggedBigUint {
+    const TAG: u64 = tags::POSITIVE_BIGNUM;
+    type Body = ByteBuf;
+
+    fn to_body(&self) -> ByteBuf {
+        ByteBuf::from(self.0.to_bytes_be())
+    }
+
+    fn from_body(body: ByteBuf) -> StdResult<Self, String> {
+        Ok(TaggedBigUint(BigUint::from_bytes_be(&body.into_vec())))
+    }
+}
+
+/// A `BigInt` as a CBOR bignum: tag 2 when non-negative, tag 3 (`-1 - n`
+/// magnitude) when negative. Spans two tags, so it drives the tag primitives
+/// directly instead of implementing [`TaggedValue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedBigInt(pub BigInt);
+
+impl Serialize for TaggedBigInt {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (tag, magnitude) = match self.0.sign() {
+            Sign::Minus => {
+                // store -1 - n, i.e. the magnitude of (-n - 1)
+                let adjusted = (-&self.0) - 1u8;
+                (tags::NEGATIVE_BIGNUM, adjusted.to_biguint().unwrap())
+            }
+            _ => (tags::POSITIVE_BIGNUM, self.0.to_biguint().unwrap()),
+        };
+        let body = ByteBuf::from(magnitude.to_bytes_be());
+        serde_cbor::EncodeCborTag::new(tag, &body).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TaggedBigInt {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let wrapper = serde_cbor::EncodeCborTag::deserialize(deserializer)?;
+        let tag = wrapper.tag();
+        let body: ByteBuf = wrapper.value();
+        let magnitude = BigUint::from_bytes_be(&body.into_vec());
+        match tag {
+            tags::POSITIVE_BIGNUM => Ok(TaggedBigInt(BigInt::from(magnitude))),
+            tags::NEGATIVE_BIGNUM => {
+                Ok(TaggedBigInt(-BigInt::from(magnitude) - 1))
+            }
+            other => Err(de::Error::custom(format!(
+                "Invalid tag: {}, expected {} or {}",
+                other,
+                tags::POSITIVE_BIGNUM,
+                tags::NEGATIVE_BIGNUM
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_biguint_roundtrip() {
+        let n = TaggedBigUint(BigUint::parse_bytes(b"123456789012345678901234567890", 10).unwrap());
+        let bytes = serde_cbor::to_vec(&n).unwrap();
+        let back: TaggedBigUint = serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(n, back);
+    }
+
+    #[test]
+    fn test_bigint_roundtrip() {
+        for s in &["0", "1", "-1", "987654321987654321", "-987654321987654321"] {
+            let n = TaggedBigInt(BigInt::parse_bytes(s.as_bytes(), 10).unwrap());
+            let bytes = serde_cbor::to_vec(&n).unwrap();
+            let back: TaggedBigInt = serde_cbor::from_slice(&bytes).unwrap();
+            assert_eq!(n, back, "roundtrip failed for {}", s);
+        }
+    }
+}